@@ -0,0 +1,37 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![feature(test)]
+extern crate test;
+extern crate fringe;
+
+use fringe::{OsStack, StackPool};
+
+#[bench]
+fn os_stack(b: &mut test::Bencher) {
+  b.iter(|| test::black_box(OsStack::new(0).unwrap()));
+}
+
+#[bench]
+fn pooled_stack(b: &mut test::Bencher) {
+  b.iter(|| test::black_box(StackPool::get(0).unwrap()));
+}
+
+#[bench]
+fn os_stack_batch(b: &mut test::Bencher) {
+  b.iter(|| {
+    let stacks: Vec<_> = (0..64).map(|_| OsStack::new(1 << 16).unwrap()).collect();
+    test::black_box(stacks)
+  });
+}
+
+#[bench]
+fn pooled_stack_batch(b: &mut test::Bencher) {
+  b.iter(|| {
+    let stacks: Vec<_> = (0..64).map(|_| StackPool::get(1 << 16).unwrap()).collect();
+    test::black_box(stacks)
+  });
+}
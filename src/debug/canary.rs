@@ -0,0 +1,53 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+use core::{cmp, ptr, slice};
+use stack;
+
+/// Number of bytes at the bottom of the stack filled with the canary pattern.
+/// Small stacks use at most an eighth of their size for the canary.
+const CANARY_SIZE: usize = 128;
+const CANARY_BYTE: u8   = 0xca;
+
+#[derive(Debug)]
+pub struct Canary(bool);
+
+impl Canary {
+  #[inline(always)]
+  pub unsafe fn place<Stack: stack::Stack>(stack: &Stack) -> Canary {
+    ptr::write_bytes(stack.limit(), CANARY_BYTE, canary_len(stack));
+    Canary(true)
+  }
+
+  #[inline(always)]
+  pub fn disarmed() -> Canary {
+    Canary(false)
+  }
+
+  #[inline(always)]
+  pub fn check<Stack: stack::Stack>(&self, stack: &Stack, name: Option<&str>) {
+    if !self.0 { return }
+
+    let canary = unsafe {
+      slice::from_raw_parts(stack.limit() as *const u8, canary_len(stack))
+    };
+    if canary.iter().any(|&byte| byte != CANARY_BYTE) {
+      panic!("generator '{}' has overflowed its stack (canary at {:p} clobbered)",
+             name.unwrap_or("<unnamed>"), stack.limit())
+    }
+  }
+
+  #[inline(always)]
+  pub fn len<Stack: stack::Stack>(&self, stack: &Stack) -> usize {
+    if self.0 { canary_len(stack) } else { 0 }
+  }
+}
+
+#[inline(always)]
+fn canary_len<Stack: stack::Stack>(stack: &Stack) -> usize {
+  let size = stack.base() as usize - stack.limit() as usize;
+  cmp::min(CANARY_SIZE, size / 8)
+}
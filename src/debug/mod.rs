@@ -22,3 +22,61 @@ mod imp {
     }
   }
 }
+
+pub use self::canary::Canary;
+
+#[cfg(feature = "canary")]
+#[path = "canary.rs"]
+mod canary;
+
+#[cfg(not(feature = "canary"))]
+mod canary {
+  use stack;
+  #[derive(Debug)]
+  pub struct Canary;
+  /// No-op since no canary
+  impl Canary {
+    #[inline(always)]
+    pub unsafe fn place<Stack: stack::Stack>(_stack: &Stack) -> Canary {
+      Canary
+    }
+
+    #[inline(always)]
+    pub fn disarmed() -> Canary {
+      Canary
+    }
+
+    #[inline(always)]
+    pub fn check<Stack: stack::Stack>(&self, _stack: &Stack, _name: Option<&str>) {}
+
+    #[inline(always)]
+    pub fn len<Stack: stack::Stack>(&self, _stack: &Stack) -> usize {
+      0
+    }
+  }
+}
+
+#[cfg(unix)]
+pub use os::overflow::GuardId;
+
+#[cfg(not(unix))]
+pub use self::guard::GuardId;
+
+#[cfg(not(unix))]
+mod guard {
+  use stack;
+  #[derive(Debug)]
+  pub struct GuardId;
+  /// No-op since no overflow handler
+  impl GuardId {
+    pub fn unregistered() -> GuardId {
+      GuardId
+    }
+
+    pub fn register<Stack: stack::GuardedStack + stack::Stack>(_stack: &Stack) -> GuardId {
+      GuardId
+    }
+
+    pub fn set_name(&self, _name: &'static str) {}
+  }
+}
@@ -82,8 +82,13 @@ pub enum State {
 #[derive(Debug)]
 pub struct Generator<Input: Send, Output: Send, Stack: stack::Stack> {
   state:     State,
+  name:      Option<&'static str>,
+  // Fields are dropped in declaration order; the guard area must be
+  // unregistered before the stack is unmapped.
+  guard_id:  debug::GuardId,
   stack:     Stack,
   stack_id:  debug::StackId,
+  canary:    debug::Canary,
   stack_ptr: StackPointer,
   phantom:   PhantomData<(*const Input, *const Output)>
 }
@@ -96,7 +101,10 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
   pub fn new<F>(stack: Stack, f: F) -> Generator<Input, Output, Stack>
       where Stack: stack::GuardedStack,
             F: FnOnce(&mut Yielder<Input, Output>, Input) + Send {
-    unsafe { Generator::unsafe_new(stack, f) }
+    // Overflows are caught by the guard page; there is no need for a canary.
+    let mut generator = unsafe { Generator::new_inner(stack, f, false) };
+    generator.guard_id = debug::GuardId::register(&generator.stack);
+    generator
   }
 
   /// Same as `new`, but does not require `stack` to have a guard page.
@@ -105,9 +113,19 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
   /// memory safety by overflowing the stack. It is useful in environments where
   /// guarded stacks do not exist, e.g. in absence of an MMU.
   ///
+  /// With the `canary` feature enabled, the bottom of the stack is filled with
+  /// a known pattern, which is checked every time the generator suspends or returns;
+  /// if it has been overwritten, `resume()` panics. This catches most, but not all,
+  /// stack overflows, and only after they have already corrupted memory.
+  ///
   /// See also the [contract](../trait.Stack.html) that needs to be fulfilled by `stack`.
   pub unsafe fn unsafe_new<F>(stack: Stack, f: F) -> Generator<Input, Output, Stack>
       where F: FnOnce(&mut Yielder<Input, Output>, Input) + Send {
+    Generator::new_inner(stack, f, true)
+  }
+
+  unsafe fn new_inner<F>(stack: Stack, f: F, place_canary: bool) -> Generator<Input, Output, Stack>
+      where F: FnOnce(&mut Yielder<Input, Output>, Input) + Send {
     unsafe extern "C" fn generator_wrapper<Input, Output, Stack, F>(env: usize, stack_ptr: StackPointer) -> !
         where Input: Send, Output: Send, Stack: stack::Stack,
               F: FnOnce(&mut Yielder<Input, Output>, Input) {
@@ -124,6 +142,7 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
     }
 
     let stack_id  = debug::StackId::register(&stack);
+    let canary    = if place_canary { debug::Canary::place(&stack) } else { debug::Canary::disarmed() };
     let stack_ptr = StackPointer::init(&stack, generator_wrapper::<Input, Output, Stack, F>);
 
     // Transfer environment to the callee.
@@ -132,8 +151,11 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
 
     Generator {
       state:     State::Runnable,
+      name:      None,
+      guard_id:  debug::GuardId::unregistered(),
       stack:     stack,
       stack_id:  stack_id,
+      canary:    canary,
       stack_ptr: stack_ptr,
       phantom:   PhantomData
     }
@@ -155,6 +177,8 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
           let (data_out, stack_ptr) = StackPointer::swap(&input as *const Input as usize, self.stack_ptr, Some(&self.stack));
           self.stack_ptr = stack_ptr;
           mem::forget(input);
+          // Don't touch the yielded value if the stack has been corrupted.
+          self.canary.check(&self.stack, self.name);
           ptr::read(data_out as *const Option<Output>)
         };
 
@@ -172,6 +196,49 @@ impl<Input, Output, Stack> Generator<Input, Output, Stack>
   #[inline]
   pub fn state(&self) -> State { self.state }
 
+  /// Sets the name of the generator. The name is used to identify the generator
+  /// when its stack overflows; see [install_overflow_handler][handler].
+  ///
+  /// [handler]: ../fn.install_overflow_handler.html
+  pub fn set_name(&mut self, name: &'static str) {
+    self.name = Some(name);
+    self.guard_id.set_name(name)
+  }
+
+  /// Returns the name of the generator, if one has been set.
+  #[inline]
+  pub fn name(&self) -> Option<&'static str> { self.name }
+
+  /// Releases the memory backing the unused part of the generator stack, i.e. the pages
+  /// below the stack pointer of the suspended generator function, to the operating system.
+  /// The address space stays reserved, and the pages are committed again when touched.
+  ///
+  /// A page immediately below the stack pointer is kept as a safety margin.
+  /// If the generator function has returned, every page of the stack is released.
+  ///
+  /// This is useful for long-lived generators that have once used a lot of stack,
+  /// but now spend most of their time suspended with a shallow call stack.
+  #[cfg(unix)]
+  pub fn trim_stack(&mut self) -> Result<(), ::std::io::Error> {
+    unsafe {
+      match self.state {
+        State::Runnable    => {
+          // The canary is checked on the next resume, so it must not be released.
+          let keep = self.canary.len(&self.stack);
+          ::os::trim_stack(&self.stack, self.stack_ptr.0 as *mut u8, keep)
+        }
+        // The generator cannot be switched to anymore, so nothing on the stack is live.
+        State::Unavailable => ::os::release_stack(&self.stack)
+      }
+    }
+  }
+
+  /// Returns the amount of memory reserved for, and committed to, the generator stack.
+  #[cfg(unix)]
+  pub fn stack_stats(&self) -> Result<::os::StackStats, ::std::io::Error> {
+    ::os::stack_stats(&self.stack)
+  }
+
   /// Extracts the stack from a generator when the generator function has returned.
   /// If the generator function has not returned
   /// (i.e. `self.state() == State::Runnable`), panics.
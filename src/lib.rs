@@ -28,9 +28,13 @@
 //!   * a stack allocator based on `Box<[u8]>`,
 //!     [OwnedStack](struct.OwnedStack.html);
 //!   * a stack allocator based on anonymous memory mappings with guard pages,
-//!     [OsStack](struct.OsStack.html).
+//!     [OsStack](struct.OsStack.html);
+//!   * a pool recycling such stacks between generators,
+//!     [StackPool](struct.StackPool.html);
+//!   * an arena carving many guarded stacks out of one memory reservation,
+//!     [StackArena](struct.StackArena.html).
 
-#[cfg(test)]
+#[cfg(any(test, unix))]
 #[macro_use]
 extern crate std;
 
@@ -44,6 +48,16 @@ pub use owned_stack::OwnedStack;
 
 #[cfg(unix)]
 pub use os::Stack as OsStack;
+#[cfg(unix)]
+pub use os::{Builder as OsStackBuilder, Error as OsStackError};
+#[cfg(unix)]
+pub use os::{StackPool, PooledStack, PoolConfig};
+#[cfg(unix)]
+pub use os::{StackArena, ArenaStack};
+#[cfg(unix)]
+pub use os::StackStats;
+#[cfg(unix)]
+pub use os::install_overflow_handler;
 
 mod arch;
 
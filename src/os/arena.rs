@@ -0,0 +1,167 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use self::std::io::{Error as IoError, ErrorKind};
+use self::std::vec::Vec;
+use self::std::sync::{Mutex, MutexGuard};
+use stack;
+use super::sys;
+
+/// StackArena carves many guarded stacks of the same size out of a single
+/// memory reservation.
+///
+/// The reservation is made up front and does not count against the overcommit
+/// limit; the pages of a stack are only committed when they are first touched,
+/// and are released back to the operating system when the stack is freed.
+/// Every stack is preceded by its own guard page.
+///
+/// Note that the operating system still tracks every guard page as a separate
+/// mapping, so the number of stacks in an arena remains bounded by the
+/// per-process mapping limit (`vm.max_map_count` on Linux).
+///
+/// # Example
+///
+/// ```
+/// use fringe::{StackArena, Generator};
+///
+/// let arena = StackArena::new(1 << 16, 1000).unwrap();
+/// let mut gens: Vec<_> = (0..1000).map(|i| {
+///   let stack = arena.get().unwrap();
+///   Generator::new(stack, move |yielder, ()| yielder.suspend(i))
+/// }).collect();
+/// assert_eq!(gens[999].resume(()), Some(999));
+/// ```
+#[derive(Debug)]
+pub struct StackArena {
+  ptr:       *mut u8,
+  len:       usize,
+  stack_len: usize,
+  free:      Mutex<Vec<usize>>
+}
+
+unsafe impl Send for StackArena {}
+unsafe impl Sync for StackArena {}
+
+impl StackArena {
+  /// Reserves memory for `count` stacks with at least `size` accessible bytes each.
+  /// As with `OsStack::new`, `size` is rounded up to an integral number of pages,
+  /// and a `size` of zero results in one-page stacks.
+  ///
+  /// Fails with `ErrorKind::InvalidInput` if `count` is zero, or if the arena
+  /// would not fit into the address space.
+  pub fn new(size: usize, count: usize) -> Result<StackArena, IoError> {
+    let page_size = sys::page_size();
+
+    if count == 0 {
+      return Err(IoError::new(ErrorKind::InvalidInput, "stack arena must hold at least one stack"))
+    }
+
+    // Stacks have to be at least one page long.
+    let stack_len = if size == 0 { page_size } else { size };
+
+    // Round the length one page size up, using the fact that the page size
+    // is a power of two, and increase it to fit the guard page. Check for
+    // overflow, since the guard pages are marked based on these lengths.
+    let stack_len = stack_len.checked_add(page_size - 1)
+                             .and_then(|len| (len & !(page_size - 1)).checked_add(page_size));
+    let len = stack_len.and_then(|stack_len| stack_len.checked_mul(count));
+    let (stack_len, len) = match (stack_len, len) {
+      (Some(stack_len), Some(len)) => (stack_len, len),
+      _ => return Err(IoError::new(ErrorKind::InvalidInput, "stack arena is too large"))
+    };
+
+    // Reserve memory for all stacks.
+    let arena = StackArena {
+      ptr:       try!(unsafe { sys::map_stack(len, true) }),
+      len:       len,
+      stack_len: stack_len,
+      free:      Mutex::new((0..count).rev().collect())
+    };
+
+    // Mark the guard pages. If this fails, `arena` will be dropped,
+    // unmapping it.
+    for index in 0..count {
+      try!(unsafe { sys::protect_stack(arena.stack_ptr(index), page_size) });
+    }
+
+    Ok(arena)
+  }
+
+  /// Takes a stack from the arena, or returns `None` if every stack is in use.
+  pub fn get(&self) -> Option<ArenaStack> {
+    let index = self.lock_free().pop();
+    index.map(|index| ArenaStack { arena: self, index: index })
+  }
+
+  /// Returns the number of stacks in the arena.
+  pub fn capacity(&self) -> usize {
+    self.len / self.stack_len
+  }
+
+  /// Returns the number of stacks that are not in use.
+  pub fn available(&self) -> usize {
+    self.lock_free().len()
+  }
+
+  #[inline(always)]
+  fn stack_ptr(&self, index: usize) -> *mut u8 {
+    unsafe { self.ptr.offset((index * self.stack_len) as isize) }
+  }
+
+  fn lock_free(&self) -> MutexGuard<Vec<usize>> {
+    // The free list is only ever pushed to or popped from,
+    // so a panic cannot have left it inconsistent.
+    self.free.lock().unwrap_or_else(|err| err.into_inner())
+  }
+}
+
+impl Drop for StackArena {
+  fn drop(&mut self) {
+    unsafe { sys::unmap_stack(self.ptr, self.len) }.expect("cannot unmap stack arena")
+  }
+}
+
+/// ArenaStack holds a guarded stack taken from a [stack arena](struct.StackArena.html).
+/// The stack is returned to the arena when dropped.
+#[derive(Debug)]
+pub struct ArenaStack<'a> {
+  arena: &'a StackArena,
+  index: usize
+}
+
+impl<'a> stack::Stack for ArenaStack<'a> {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    unsafe {
+      self.arena.stack_ptr(self.index).offset(self.arena.stack_len as isize)
+    }
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    unsafe {
+      self.arena.stack_ptr(self.index).offset(sys::page_size() as isize)
+    }
+  }
+}
+
+unsafe impl<'a> stack::GuardedStack for ArenaStack<'a> {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    sys::page_size()
+  }
+}
+
+impl<'a> Drop for ArenaStack<'a> {
+  fn drop(&mut self) {
+    let limit = stack::Stack::limit(self);
+    let len = self.arena.stack_len - sys::page_size();
+    unsafe { sys::discard_stack(limit, len) }.expect("cannot discard stack");
+    self.arena.lock_free().push(self.index)
+  }
+}
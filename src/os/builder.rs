@@ -0,0 +1,220 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use self::std::io::{Error as IoError, ErrorKind};
+use self::std::{cmp, fmt, error};
+use super::{sys, Stack};
+
+// An upper bound on the number of guard pages, so that guard areas cannot
+// take up an unreasonable part of the address space.
+const MAX_GUARD_PAGES: usize = 4096;
+
+/// OsStackBuilder configures how the memory of an [OsStack](struct.OsStack.html)
+/// is mapped.
+///
+/// # Example
+///
+/// ```
+/// use fringe::OsStackBuilder;
+///
+/// let stack = OsStackBuilder::new(1 << 16)
+///   .guard_pages(4)
+///   .huge_pages(false)
+///   .build()
+///   .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+  size:        usize,
+  guard_pages: usize,
+  prefault:    bool,
+  lock:        bool,
+  huge_pages:  Option<bool>,
+  dontdump:    bool,
+  noreserve:   bool
+}
+
+impl Builder {
+  /// Creates a builder for a stack with at least `size` accessible bytes
+  /// and a single guard page, mapped the same way as by `OsStack::new(size)`.
+  pub fn new(size: usize) -> Builder {
+    Builder {
+      size:        size,
+      guard_pages: 1,
+      prefault:    false,
+      lock:        false,
+      huge_pages:  None,
+      dontdump:    false,
+      noreserve:   false
+    }
+  }
+
+  /// Sets the number of guard pages below the stack. At least one guard page
+  /// is always present.
+  ///
+  /// Values above 4096 are silently clamped to 4096 pages; use
+  /// `GuardedStack::guard_size` on the built stack to find out the actual size
+  /// of the guard area.
+  pub fn guard_pages(mut self, pages: usize) -> Builder {
+    self.guard_pages = cmp::min(cmp::max(pages, 1), MAX_GUARD_PAGES);
+    self
+  }
+
+  /// Prefaults the stack, committing memory for it up front.
+  pub fn prefault(mut self, prefault: bool) -> Builder {
+    self.prefault = prefault;
+    self
+  }
+
+  /// Locks the stack in memory, so that it is never paged out.
+  pub fn lock(mut self, lock: bool) -> Builder {
+    self.lock = lock;
+    self
+  }
+
+  /// Enables or disables transparent huge pages for the stack. If unset,
+  /// the system default is used. Only has an effect on Linux.
+  pub fn huge_pages(mut self, enable: bool) -> Builder {
+    self.huge_pages = Some(enable);
+    self
+  }
+
+  /// Excludes the stack from core dumps. Building the stack fails with
+  /// `OsStackError::ExcludeFromDump` on platforms that cannot do that.
+  pub fn exclude_from_core_dumps(mut self, exclude: bool) -> Builder {
+    self.dontdump = exclude;
+    self
+  }
+
+  /// Reserves address space for the stack without it counting against
+  /// the overcommit limit. Only has an effect on Linux.
+  pub fn noreserve(mut self, noreserve: bool) -> Builder {
+    self.noreserve = noreserve;
+    self
+  }
+
+  /// Allocates the stack.
+  pub fn build(&self) -> Result<Stack, Error> {
+    let page_size = sys::page_size();
+
+    // Stacks have to be at least one page long.
+    let len = if self.size == 0 { page_size } else { self.size };
+
+    // Round the length one page size up, using the fact that the page size
+    // is a power of two, and increase it to fit the guard pages. Check for
+    // overflow, since a guard area that wrapped around to zero bytes would
+    // leave the stack unguarded.
+    let guard_len = self.guard_pages.checked_mul(page_size);
+    let len = len.checked_add(page_size - 1)
+                 .and_then(|len| guard_len.and_then(|guard_len|
+                   (len & !(page_size - 1)).checked_add(guard_len)));
+    let (len, guard_len) = match (len, guard_len) {
+      (Some(len), Some(guard_len)) => (len, guard_len),
+      _ => return Err(Error::Size)
+    };
+
+    // Allocate a stack.
+    let stack = Stack {
+      ptr:       try!(unsafe { sys::map_stack(len, self.noreserve) }.map_err(Error::Map)),
+      len:       len,
+      guard_len: guard_len
+    };
+
+    // Everything below operates on the stack itself, excluding the guard pages.
+    let (data_ptr, data_len) = unsafe { (stack.ptr.offset(guard_len as isize), len - guard_len) };
+
+    // Mark the guard pages. If this or any of the following steps fail,
+    // `stack` will be dropped, unmapping it.
+    try!(unsafe { sys::protect_stack(stack.ptr, guard_len) }.map_err(Error::Protect));
+
+    if let Some(enable) = self.huge_pages {
+      try!(unsafe { sys::advise_huge_pages(data_ptr, data_len, enable) }.map_err(Error::HugePages));
+    }
+
+    if self.dontdump {
+      try!(unsafe { sys::exclude_from_dump(data_ptr, data_len) }.map_err(Error::ExcludeFromDump));
+    }
+
+    // Prefault the stack itself, but not the guard pages, after the advice
+    // above, which only applies to memory committed after it is given.
+    if self.prefault {
+      unsafe { sys::prefault_stack(data_ptr, data_len) }
+    }
+
+    if self.lock {
+      try!(unsafe { sys::lock_stack(data_ptr, data_len) }.map_err(Error::Lock));
+    }
+
+    Ok(stack)
+  }
+}
+
+/// OsStackError describes which step of allocating an [OsStack](struct.OsStack.html)
+/// has failed, and why.
+#[derive(Debug)]
+pub enum Error {
+  /// The stack, including its guard pages, is larger than the address space.
+  Size,
+  /// Mapping memory for the stack has failed.
+  Map(IoError),
+  /// Making the guard pages inaccessible has failed.
+  Protect(IoError),
+  /// Enabling or disabling transparent huge pages has failed.
+  HugePages(IoError),
+  /// Excluding the stack from core dumps has failed.
+  ExcludeFromDump(IoError),
+  /// Locking the stack in memory has failed.
+  Lock(IoError)
+}
+
+impl Error {
+  /// Returns the operating system error that caused the failure, if any.
+  pub fn io_error(&self) -> Option<&IoError> {
+    match *self {
+      Error::Size => None,
+      Error::Map(ref err) | Error::Protect(ref err) | Error::HugePages(ref err) |
+      Error::ExcludeFromDump(ref err) | Error::Lock(ref err) => Some(err)
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.io_error() {
+      Some(err) => write!(f, "{}: {}", error::Error::description(self), err),
+      None => write!(f, "{}", error::Error::description(self))
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn description(&self) -> &str {
+    match *self {
+      Error::Size               => "stack is too large",
+      Error::Map(_)             => "cannot map stack",
+      Error::Protect(_)         => "cannot protect stack guard pages",
+      Error::HugePages(_)       => "cannot configure huge pages for stack",
+      Error::ExcludeFromDump(_) => "cannot exclude stack from core dumps",
+      Error::Lock(_)            => "cannot lock stack in memory"
+    }
+  }
+
+  fn cause(&self) -> Option<&error::Error> {
+    self.io_error().map(|err| err as &error::Error)
+  }
+}
+
+impl From<Error> for IoError {
+  fn from(err: Error) -> IoError {
+    match err {
+      Error::Size => IoError::new(ErrorKind::InvalidInput, "stack is too large"),
+      Error::Map(err) | Error::Protect(err) | Error::HugePages(err) |
+      Error::ExcludeFromDump(err) | Error::Lock(err) => err
+    }
+  }
+}
@@ -6,16 +6,27 @@
 // copied, modified, or distributed except according to those terms.
 extern crate std;
 use self::std::io::Error as IoError;
+use self::std::cmp;
 use stack;
 
 mod sys;
+mod pool;
+mod arena;
+mod builder;
+pub mod overflow;
+
+pub use self::builder::{Builder, Error};
+pub use self::overflow::install_overflow_handler;
+pub use self::pool::{StackPool, PooledStack, PoolConfig};
+pub use self::arena::{StackArena, ArenaStack};
 
 /// OsStack holds a guarded stack allocated using the operating system's anonymous
 /// memory mapping facility.
 #[derive(Debug)]
 pub struct Stack {
-  ptr: *mut u8,
-  len: usize
+  ptr:       *mut u8,
+  len:       usize,
+  guard_len: usize
 }
 
 unsafe impl Send for Stack {}
@@ -25,30 +36,11 @@ impl Stack {
   /// `size` is rounded up to an integral number of pages; `Stack::new(0)` is legal
   /// and allocates the smallest possible stack, consisting of one data page and
   /// one guard page.
+  ///
+  /// See [OsStackBuilder](struct.OsStackBuilder.html) for more control over
+  /// how the stack is mapped.
   pub fn new(size: usize) -> Result<Stack, IoError> {
-    let page_size = sys::page_size();
-
-    // Stacks have to be at least one page long.
-    let len = if size == 0 { page_size } else { size };
-
-    // Round the length one page size up, using the fact that the page size
-    // is a power of two.
-    let len = (len + page_size - 1) & !(page_size - 1);
-
-    // Increase the length to fit the guard page.
-    let len = len + page_size;
-
-    // Allocate a stack.
-    let stack = Stack {
-      ptr: try!(unsafe { sys::map_stack(len) }),
-      len: len
-    };
-
-    // Mark the guard page. If this fails, `stack` will be dropped,
-    // unmapping it.
-    try!(unsafe { sys::protect_stack(stack.ptr) });
-
-    Ok(stack)
+    Builder::new(size).build().map_err(IoError::from)
   }
 }
 
@@ -63,15 +55,82 @@ impl stack::Stack for Stack {
   #[inline(always)]
   fn limit(&self) -> *mut u8 {
     unsafe {
-      self.ptr.offset(sys::page_size() as isize)
+      self.ptr.offset(self.guard_len as isize)
     }
   }
 }
 
-unsafe impl stack::GuardedStack for Stack {}
+unsafe impl stack::GuardedStack for Stack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    self.guard_len
+  }
+}
 
 impl Drop for Stack {
   fn drop(&mut self) {
     unsafe { sys::unmap_stack(self.ptr, self.len) }.expect("cannot unmap stack")
   }
 }
+
+/// StackStats describes how much of a stack is backed by physical memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackStats {
+  /// Number of bytes of address space reserved for the stack, not including
+  /// any guard pages.
+  pub reserved: usize,
+  /// Number of bytes of the stack that are currently resident in memory.
+  pub committed: usize
+}
+
+/// Returns the memory usage statistics for `stack`.
+pub fn stack_stats<S: stack::Stack>(stack: &S) -> Result<StackStats, IoError> {
+  let page_size = sys::page_size();
+  let limit = stack.limit() as usize;
+  let base  = stack.base() as usize;
+
+  // Count every page that holds a part of the stack.
+  let start = limit & !(page_size - 1);
+  let end   = (base + page_size - 1) & !(page_size - 1);
+  let pages = try!(unsafe { sys::resident_pages(start as *mut u8, end - start) });
+
+  let reserved = base - limit;
+  Ok(StackStats {
+    reserved:  reserved,
+    committed: cmp::min(pages * page_size, reserved)
+  })
+}
+
+/// Releases the memory backing every page of `stack` that lies entirely
+/// below `ptr`, except for the page immediately below the page containing
+/// `ptr`, which is kept as a safety margin. The first `keep` bytes above
+/// the stack limit are preserved as well.
+///
+/// This function is unsafe because the contents of the released pages
+/// are lost; `ptr` must be at or below the lowest address holding live data.
+pub unsafe fn trim_stack<S: stack::Stack>(stack: &S, ptr: *mut u8, keep: usize)
+    -> Result<(), IoError> {
+  let page_size = sys::page_size();
+  let start = stack.limit() as usize + keep;
+  let end = (ptr as usize & !(page_size - 1)).saturating_sub(page_size);
+  discard_range(start, end)
+}
+
+/// Releases the memory backing every page that lies entirely within `stack`.
+///
+/// This function is unsafe because the contents of the stack are lost.
+pub unsafe fn release_stack<S: stack::Stack>(stack: &S) -> Result<(), IoError> {
+  discard_range(stack.limit() as usize, stack.base() as usize & !(sys::page_size() - 1))
+}
+
+// Releases every page between `start`, rounded up to a page, and `end`,
+// which must be page-aligned.
+unsafe fn discard_range(start: usize, end: usize) -> Result<(), IoError> {
+  let page_size = sys::page_size();
+  let start = (start + page_size - 1) & !(page_size - 1);
+  if end > start {
+    sys::discard_stack(start as *mut u8, end - start)
+  } else {
+    Ok(())
+  }
+}
@@ -0,0 +1,393 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+// To understand the code in this file, keep in mind that the signal handler
+// may run at any point in any thread, including while the registry is being
+// updated. The handler therefore never takes locks or allocates; it only walks
+// the list of slot chunks, which are never freed, and reads every slot through
+// atomics. A slot is published by storing its guard start address last, and
+// retracted by clearing the guard start address first.
+//
+// Registration does not take locks either, so that creating and destroying
+// generators does not serialize threads. Every thread caches a few unused
+// slots; a thread with an empty cache claims unused slots by scanning the chunks,
+// and allocates a new chunk if there are none.
+extern crate std;
+extern crate libc;
+
+use self::std::io::Error as IoError;
+use self::std::boxed::Box;
+use self::std::vec::Vec;
+use self::std::cell::RefCell;
+use self::std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+use self::std::sync::atomic::{ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+use self::std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use self::std::{cmp, ptr, mem, slice, str, fmt};
+use self::std::fmt::Write;
+use self::libc::{c_int, c_void, siginfo_t};
+use stack;
+use super::sys;
+
+/// Installs a handler for `SIGSEGV` and `SIGBUS` that reports stack overflows
+/// in generators.
+///
+/// When a generator with a guarded stack overflows into its guard area, the handler
+/// prints the name of the generator (see `Generator::set_name`) and the size of
+/// its stack, and aborts the process. Faults anywhere else are passed on to
+/// the handler that was installed previously, such as the one reporting
+/// overflows of thread stacks in the Rust standard library.
+///
+/// Only generators created after the handler is installed are checked.
+///
+/// The handler runs on an alternate signal stack, since the stack that has
+/// overflowed cannot be used to run it. The Rust standard library sets up
+/// an alternate signal stack for every thread it starts; this function sets one
+/// up for the calling thread if it does not have one yet, so it should also be
+/// called on every other thread that runs generators. Calling it more than once
+/// is harmless.
+pub fn install_overflow_handler() -> Result<(), IoError> {
+  unsafe {
+    try!(ensure_alternate_stack());
+
+    let _guard = lock_install();
+    if !INSTALLED.load(Ordering::Relaxed) {
+      try!(install());
+      INSTALLED.store(true, Ordering::Release);
+    }
+  }
+
+  Ok(())
+}
+
+/// GuardId is a registration of the guard area of a generator stack
+/// with the overflow handler. The registration is removed when dropped.
+#[derive(Debug)]
+pub struct GuardId(Option<&'static Slot>);
+
+impl GuardId {
+  /// Returns a registration that does not refer to any guard page.
+  pub fn unregistered() -> GuardId {
+    GuardId(None)
+  }
+
+  /// Registers the guard area below `stack` if the overflow handler is installed.
+  pub fn register<Stack: stack::GuardedStack + stack::Stack>(stack: &Stack) -> GuardId {
+    if !INSTALLED.load(Ordering::Acquire) {
+      return GuardId(None)
+    }
+
+    let limit = stack.limit() as usize;
+    let slot = take_slot();
+    slot.guard_end.store(limit, Ordering::Relaxed);
+    slot.stack_size.store(stack.base() as usize - limit, Ordering::Relaxed);
+    slot.name_len.store(0, Ordering::Relaxed);
+    slot.guard_start.store(limit - stack::GuardedStack::guard_size(stack), Ordering::Release);
+    GuardId(Some(slot))
+  }
+
+  /// Sets the name reported if the stack overflows.
+  pub fn set_name(&self, name: &'static str) {
+    if let Some(slot) = self.0 {
+      slot.name_len.store(0, Ordering::Relaxed);
+      slot.name_ptr.store(name.as_ptr() as usize, Ordering::Relaxed);
+      slot.name_len.store(name.len(), Ordering::Release);
+    }
+  }
+}
+
+impl Drop for GuardId {
+  fn drop(&mut self) {
+    if let Some(slot) = self.0 {
+      slot.guard_start.store(0, Ordering::Release);
+      give_slot(slot)
+    }
+  }
+}
+
+const CHUNK_LEN: usize = 64;
+const CACHE_LEN: usize = 64;
+
+#[derive(Debug)]
+pub struct Slot {
+  // Set while the slot is in use or in a thread cache.
+  claimed:     AtomicBool,
+  guard_start: AtomicUsize,
+  guard_end:   AtomicUsize,
+  stack_size:  AtomicUsize,
+  name_ptr:    AtomicUsize,
+  name_len:    AtomicUsize
+}
+
+struct Chunk {
+  slots: Vec<Slot>,
+  next:  *const Chunk
+}
+
+static INSTALLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+// Points to the most recently allocated chunk.
+static CHUNKS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+static mut PREVIOUS_SIGSEGV: Option<libc::sigaction> = None;
+static mut PREVIOUS_SIGBUS:  Option<libc::sigaction> = None;
+
+fn lock_install() -> MutexGuard<'static, ()> {
+  static INIT: Once = ONCE_INIT;
+  static mut LOCK: *const Mutex<()> = 0 as *const _;
+
+  unsafe {
+    INIT.call_once(|| LOCK = Box::into_raw(Box::new(Mutex::new(()))));
+    (*LOCK).lock().unwrap_or_else(|err| err.into_inner())
+  }
+}
+
+/// A per-thread list of claimed, unused slots.
+struct SlotCache(Vec<&'static Slot>);
+
+impl Drop for SlotCache {
+  fn drop(&mut self) {
+    for slot in self.0.drain(..) { release_slot(slot) }
+  }
+}
+
+thread_local!(static SLOT_CACHE: RefCell<SlotCache> = RefCell::new(SlotCache(Vec::new())));
+
+fn take_slot() -> &'static Slot {
+  // The thread cache may have been destroyed already if a generator is created
+  // from a destructor of another thread-local; skip to claiming a slot then.
+  let cached = SLOT_CACHE.try_with(|cache| cache.borrow_mut().0.pop()).unwrap_or(None);
+  match cached {
+    Some(slot) => slot,
+    None => claim_slots().unwrap_or_else(allocate_slots)
+  }
+}
+
+fn give_slot(slot: &'static Slot) {
+  let mut slot = Some(slot);
+  let _ = SLOT_CACHE.try_with(|cache| {
+    let mut cache = cache.borrow_mut();
+    if cache.0.len() < CACHE_LEN {
+      cache.0.push(slot.take().unwrap())
+    }
+  });
+  if let Some(slot) = slot { release_slot(slot) }
+}
+
+fn release_slot(slot: &'static Slot) {
+  slot.claimed.store(false, Ordering::Release)
+}
+
+/// Adds `slots` to the thread cache, or releases them if it is unavailable.
+fn cache_slots<I: Iterator<Item=&'static Slot>>(slots: I) {
+  let mut slots = Some(slots);
+  let _ = SLOT_CACHE.try_with(|cache| cache.borrow_mut().0.extend(slots.take().unwrap()));
+  if let Some(slots) = slots {
+    for slot in slots { release_slot(slot) }
+  }
+}
+
+/// Claims up to `CACHE_LEN` unused slots, adds all but one of them to
+/// the thread cache, and returns the remaining one.
+fn claim_slots() -> Option<&'static Slot> {
+  let mut claimed = Vec::new();
+  let mut chunk = CHUNKS.load(Ordering::Acquire) as *const Chunk;
+  while !chunk.is_null() && claimed.len() < CACHE_LEN {
+    let chunk_ref: &'static Chunk = unsafe { &*chunk };
+    for slot in &chunk_ref.slots {
+      if !slot.claimed.load(Ordering::Relaxed) &&
+         !slot.claimed.compare_and_swap(false, true, Ordering::Acquire) {
+        claimed.push(slot);
+        if claimed.len() == CACHE_LEN { break }
+      }
+    }
+    chunk = chunk_ref.next;
+  }
+
+  let slot = claimed.pop();
+  cache_slots(claimed.into_iter());
+  slot
+}
+
+/// Allocates a new chunk of slots, adds all but one of them to the thread cache,
+/// and returns the remaining one. Chunks are never freed.
+fn allocate_slots() -> &'static Slot {
+  // All slots start out claimed, since they are handed to this thread.
+  let chunk = Box::new(Chunk {
+    slots: (0..CHUNK_LEN).map(|_| Slot {
+      claimed:     AtomicBool::new(true),
+      guard_start: AtomicUsize::new(0),
+      guard_end:   AtomicUsize::new(0),
+      stack_size:  AtomicUsize::new(0),
+      name_ptr:    AtomicUsize::new(0),
+      name_len:    AtomicUsize::new(0)
+    }).collect(),
+    next: CHUNKS.load(Ordering::Acquire) as *const Chunk
+  });
+  let chunk: &'static mut Chunk = unsafe { &mut *Box::into_raw(chunk) };
+
+  // Publish the chunk to the signal handler and to other threads.
+  loop {
+    let next = chunk.next as usize;
+    let prev = CHUNKS.compare_and_swap(next, chunk as *const Chunk as usize, Ordering::AcqRel);
+    if prev == next { break }
+    chunk.next = prev as *const Chunk;
+  }
+
+  let chunk: &'static Chunk = chunk;
+  let (slot, rest) = chunk.slots.split_first().unwrap();
+  cache_slots(rest.iter());
+  slot
+}
+
+unsafe fn find_slot(addr: usize) -> Option<&'static Slot> {
+  let mut chunk = CHUNKS.load(Ordering::Acquire) as *const Chunk;
+  while !chunk.is_null() {
+    for slot in &(*chunk).slots {
+      let start = slot.guard_start.load(Ordering::Acquire);
+      if start != 0 && addr >= start && addr < slot.guard_end.load(Ordering::Relaxed) {
+        return Some(slot)
+      }
+    }
+    chunk = (*chunk).next;
+  }
+  None
+}
+
+unsafe fn ensure_alternate_stack() -> Result<(), IoError> {
+  let mut current: libc::stack_t = mem::zeroed();
+  if libc::sigaltstack(ptr::null(), &mut current) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  if current.ss_flags & libc::SS_DISABLE == 0 {
+    return Ok(())
+  }
+
+  // The alternate stack is never freed, since the thread may still receive
+  // signals while it is exiting.
+  let size = libc::SIGSTKSZ + (64 << 10);
+  let stack = libc::stack_t {
+    ss_sp:    try!(sys::map_stack(size, false)) as *mut c_void,
+    ss_flags: 0,
+    ss_size:  size
+  };
+  if libc::sigaltstack(&stack, ptr::null_mut()) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  Ok(())
+}
+
+/// Installs the handler for both signals. The previous actions are recorded
+/// before the handler is installed, since it may run as soon as it is.
+unsafe fn install() -> Result<(), IoError> {
+  PREVIOUS_SIGSEGV = Some(try!(query(libc::SIGSEGV)));
+  PREVIOUS_SIGBUS  = Some(try!(query(libc::SIGBUS)));
+
+  let mut action: libc::sigaction = mem::zeroed();
+  action.sa_sigaction = handler as usize;
+  action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+  libc::sigemptyset(&mut action.sa_mask);
+
+  if libc::sigaction(libc::SIGSEGV, &action, ptr::null_mut()) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  if libc::sigaction(libc::SIGBUS, &action, ptr::null_mut()) != 0 {
+    let err = IoError::last_os_error();
+    // Leave the signals as they were before.
+    if let Some(ref previous) = PREVIOUS_SIGSEGV {
+      libc::sigaction(libc::SIGSEGV, previous, ptr::null_mut());
+    }
+    return Err(err)
+  }
+  Ok(())
+}
+
+unsafe fn query(signum: c_int) -> Result<libc::sigaction, IoError> {
+  let mut action: libc::sigaction = mem::zeroed();
+  if libc::sigaction(signum, ptr::null(), &mut action) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  Ok(action)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn fault_address(info: *mut siginfo_t) -> usize {
+  (*info).si_addr() as usize
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn fault_address(info: *mut siginfo_t) -> usize {
+  (*info).si_addr as usize
+}
+
+extern "C" fn handler(signum: c_int, info: *mut siginfo_t, context: *mut c_void) {
+  unsafe {
+    match find_slot(fault_address(info)) {
+      Some(slot) => report(slot),
+      None => chain(signum, info, context)
+    }
+  }
+}
+
+/// A buffer for formatting messages without allocating.
+struct Message {
+  bytes: [u8; 256],
+  len:   usize
+}
+
+impl fmt::Write for Message {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    let len = cmp::min(s.len(), self.bytes.len() - self.len);
+    self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+    self.len += len;
+    Ok(())
+  }
+}
+
+unsafe fn report(slot: &Slot) -> ! {
+  let name_len = slot.name_len.load(Ordering::Acquire);
+  let name = if name_len == 0 {
+    "<unnamed>"
+  } else {
+    let name_ptr = slot.name_ptr.load(Ordering::Relaxed) as *const u8;
+    str::from_utf8_unchecked(slice::from_raw_parts(name_ptr, name_len))
+  };
+
+  let mut message = Message { bytes: [0; 256], len: 0 };
+  let _ = write!(message, "\ngenerator '{}' has overflowed its stack ({} bytes)\n\
+                           fatal runtime error: stack overflow\n",
+                 name, slot.stack_size.load(Ordering::Relaxed));
+  libc::write(libc::STDERR_FILENO, message.bytes.as_ptr() as *const c_void, message.len);
+  libc::abort()
+}
+
+unsafe fn chain(signum: c_int, info: *mut siginfo_t, context: *mut c_void) {
+  let previous = match signum {
+    libc::SIGSEGV => PREVIOUS_SIGSEGV,
+    _             => PREVIOUS_SIGBUS
+  };
+
+  match previous {
+    Some(ref action) if action.sa_sigaction != libc::SIG_DFL &&
+                        action.sa_sigaction != libc::SIG_IGN => {
+      if action.sa_flags & libc::SA_SIGINFO != 0 {
+        let handler: extern "C" fn(c_int, *mut siginfo_t, *mut c_void) =
+          mem::transmute(action.sa_sigaction);
+        handler(signum, info, context)
+      } else {
+        let handler: extern "C" fn(c_int) = mem::transmute(action.sa_sigaction);
+        handler(signum)
+      }
+    }
+    _ => {
+      // Restore the default action and return; the faulting instruction
+      // will fault again and terminate the process as usual.
+      let mut action: libc::sigaction = mem::zeroed();
+      action.sa_sigaction = libc::SIG_DFL;
+      libc::sigaction(signum, &action, ptr::null_mut());
+    }
+  }
+}
@@ -0,0 +1,259 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use self::std::io::{Error as IoError, ErrorKind};
+use self::std::boxed::Box;
+use self::std::vec::Vec;
+use self::std::cell::RefCell;
+use self::std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+use self::std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+use self::std::mem;
+use stack;
+use super::{sys, Stack};
+
+/// Limits on the number of stacks retained by the [stack pool](struct.StackPool.html).
+///
+/// Both limits apply to each size class separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+  /// Maximum number of stacks cached by every thread. Stacks in a thread cache
+  /// are handed out without taking any locks.
+  pub thread_cache: usize,
+  /// Maximum number of stacks cached in the global pool shared by all threads.
+  /// Stacks that do not fit into the thread cache or the global pool are unmapped.
+  pub global_cache: usize
+}
+
+impl Default for PoolConfig {
+  fn default() -> PoolConfig {
+    PoolConfig {
+      thread_cache: 16,
+      global_cache: 64
+    }
+  }
+}
+
+/// StackPool recycles guarded stacks, avoiding the cost of mapping and unmapping
+/// memory every time a short-lived generator is created.
+///
+/// Stacks are grouped into size classes; the size of a stack is rounded up to
+/// a power-of-two number of pages. Freed stacks are first returned to a cache
+/// owned by the current thread, and, once that is full, to a global pool shared
+/// by all threads. When a thread exits, its cache is moved into the global pool.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{StackPool, Generator};
+///
+/// for i in 0..10 {
+///   let stack = StackPool::get(1 << 16).unwrap();
+///   let mut gen = Generator::new(stack, move |yielder, ()| yielder.suspend(i));
+///   assert_eq!(gen.resume(()), Some(i));
+///   // Dropping the generator returns the stack to the pool.
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StackPool;
+
+impl StackPool {
+  /// Returns a stack with at least `size` accessible bytes from the pool,
+  /// allocating a new one if no stack of a suitable size class is cached.
+  /// Like `OsStack::new(0)`, `StackPool::get(0)` returns a one-page stack.
+  ///
+  /// Fails with `ErrorKind::InvalidInput` if the size class of `size` would not
+  /// fit into the address space.
+  pub fn get(size: usize) -> Result<PooledStack, IoError> {
+    let class = match size_class(size) {
+      Some(class) => class,
+      None => return Err(IoError::new(ErrorKind::InvalidInput, "stack is too large"))
+    };
+
+    let cached = CACHE.try_with(|cache| cache.borrow_mut().take(class)).unwrap_or(None);
+    let cached = match cached {
+      Some(stack) => Some(stack),
+      None => global_take(class)
+    };
+
+    let stack = match cached {
+      Some(stack) => stack,
+      None => try!(Stack::new(class_size(class)))
+    };
+
+    Ok(PooledStack {
+      stack: Some(stack),
+      class: class
+    })
+  }
+
+  /// Changes the limits on the number of cached stacks. The new limits apply
+  /// to stacks freed after this call; stacks already cached are not released.
+  pub fn configure(config: PoolConfig) {
+    store_limit(&THREAD_CACHE, config.thread_cache);
+    store_limit(&GLOBAL_CACHE, config.global_cache);
+  }
+
+  /// Returns the current limits on the number of cached stacks.
+  pub fn config() -> PoolConfig {
+    let default = PoolConfig::default();
+    PoolConfig {
+      thread_cache: load_limit(&THREAD_CACHE, default.thread_cache),
+      global_cache: load_limit(&GLOBAL_CACHE, default.global_cache)
+    }
+  }
+
+  /// Unmaps every stack cached by the current thread and by the global pool.
+  pub fn clear() {
+    let _ = CACHE.try_with(|cache| cache.borrow_mut().0.clear());
+    let classes = mem::replace(&mut *lock_global(), Vec::new());
+    drop(classes)
+  }
+}
+
+/// PooledStack holds a guarded stack borrowed from the [stack pool](struct.StackPool.html).
+/// The stack is returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledStack {
+  // Always `Some`, except while being returned to the pool.
+  stack: Option<Stack>,
+  class: usize
+}
+
+impl PooledStack {
+  #[inline(always)]
+  fn stack(&self) -> &Stack {
+    self.stack.as_ref().unwrap()
+  }
+}
+
+impl stack::Stack for PooledStack {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    stack::Stack::base(self.stack())
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    stack::Stack::limit(self.stack())
+  }
+}
+
+unsafe impl stack::GuardedStack for PooledStack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    stack::GuardedStack::guard_size(self.stack())
+  }
+}
+
+impl Drop for PooledStack {
+  fn drop(&mut self) {
+    let class = self.class;
+    let mut stack = self.stack.take();
+
+    // The thread cache may have been destroyed already if the stack is dropped
+    // from a destructor of another thread-local; skip to the global pool then.
+    let _ = CACHE.try_with(|cache| cache.borrow_mut().give(class, &mut stack));
+
+    if let Some(stack) = stack {
+      global_give(class, stack)
+    }
+  }
+}
+
+// The limits are stored biased by one, so that the zero initializer can stand
+// for "not configured" while zero remains a valid limit.
+static THREAD_CACHE: AtomicUsize = ATOMIC_USIZE_INIT;
+static GLOBAL_CACHE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn store_limit(limit: &AtomicUsize, value: usize) {
+  limit.store(value.saturating_add(1), Ordering::Relaxed)
+}
+
+fn load_limit(limit: &AtomicUsize, default: usize) -> usize {
+  match limit.load(Ordering::Relaxed) {
+    0 => default,
+    value => value - 1
+  }
+}
+
+/// Returns the size class of stacks with at least `size` bytes, or `None` if
+/// the stacks of that class would be larger than the address space.
+fn size_class(size: usize) -> Option<usize> {
+  let page_size = sys::page_size();
+  let pages = size / page_size + if size % page_size == 0 { 0 } else { 1 };
+  let pages = if pages == 0 { 1 } else { pages };
+  pages.checked_next_power_of_two()
+       .and_then(|pages| pages.checked_mul(page_size))
+       .map(|_| pages.next_power_of_two().trailing_zeros() as usize)
+}
+
+fn class_size(class: usize) -> usize {
+  sys::page_size() << class
+}
+
+/// A per-size-class list of cached stacks.
+#[derive(Debug)]
+struct Cache(Vec<Vec<Stack>>);
+
+impl Cache {
+  fn new() -> Cache {
+    Cache(Vec::new())
+  }
+
+  fn take(&mut self, class: usize) -> Option<Stack> {
+    self.0.get_mut(class).and_then(|stacks| stacks.pop())
+  }
+
+  /// Moves the stack out of `stack` into the cache if there is room for it.
+  fn give(&mut self, class: usize, stack: &mut Option<Stack>) {
+    let limit = load_limit(&THREAD_CACHE, PoolConfig::default().thread_cache);
+    while self.0.len() <= class { self.0.push(Vec::new()) }
+    if self.0[class].len() < limit {
+      if let Some(stack) = stack.take() { self.0[class].push(stack) }
+    }
+  }
+}
+
+impl Drop for Cache {
+  fn drop(&mut self) {
+    for (class, stacks) in self.0.drain(..).enumerate() {
+      for stack in stacks { global_give(class, stack) }
+    }
+  }
+}
+
+thread_local!(static CACHE: RefCell<Cache> = RefCell::new(Cache::new()));
+
+fn lock_global() -> MutexGuard<'static, Vec<Vec<Stack>>> {
+  static INIT: Once = ONCE_INIT;
+  static mut GLOBAL: *const Mutex<Vec<Vec<Stack>>> = 0 as *const _;
+
+  unsafe {
+    INIT.call_once(|| GLOBAL = Box::into_raw(Box::new(Mutex::new(Vec::new()))));
+    // The pool only holds unused stacks, so a panic while it was locked
+    // cannot have left it in an inconsistent state.
+    (*GLOBAL).lock().unwrap_or_else(|err| err.into_inner())
+  }
+}
+
+fn global_take(class: usize) -> Option<Stack> {
+  lock_global().get_mut(class).and_then(|stacks| stacks.pop())
+}
+
+fn global_give(class: usize, stack: Stack) {
+  let limit = load_limit(&GLOBAL_CACHE, PoolConfig::default().global_cache);
+  let mut classes = lock_global();
+  while classes.len() <= class { classes.push(Vec::new()) }
+  if classes[class].len() < limit {
+    classes[class].push(stack)
+  } else {
+    // Unmap the stack after releasing the lock.
+    drop(classes);
+    drop(stack)
+  }
+}
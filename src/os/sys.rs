@@ -9,9 +9,10 @@ extern crate libc;
 
 use self::std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
 use self::std::ptr;
+use self::std::vec::Vec;
 use self::std::io::Error as IoError;
 use self::libc::{c_void, c_int, size_t};
-use self::libc::{mmap, mprotect, munmap};
+use self::libc::{mmap, mprotect, munmap, madvise, mincore, mlock};
 use self::libc::MAP_FAILED;
 
 const GUARD_PROT:  c_int = libc::PROT_NONE;
@@ -27,8 +28,27 @@ const STACK_FLAGS: c_int = libc::MAP_STACK
 const STACK_FLAGS: c_int = libc::MAP_PRIVATE
                          | libc::MAP_ANON;
 
-pub unsafe fn map_stack(len: usize) -> Result<*mut u8, IoError> {
-  let ptr = mmap(ptr::null_mut(), len as size_t, STACK_PROT, STACK_FLAGS, -1, 0);
+// Reservations for many stacks can be far larger than what the process will
+// ever touch, so Linux allows them not to count against the overcommit limit.
+// Other platforms don't account for memory that has not been touched.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const NORESERVE_FLAGS: c_int = libc::MAP_NORESERVE;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const NORESERVE_FLAGS: c_int = 0;
+
+// On Linux, MADV_DONTNEED on a private anonymous mapping releases the pages
+// immediately, and they read as zero afterwards. Elsewhere it is merely a hint,
+// and MADV_FREE is the way to release the pages.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const DISCARD_ADVICE: c_int = libc::MADV_DONTNEED;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const DISCARD_ADVICE: c_int = libc::MADV_FREE;
+
+pub unsafe fn map_stack(len: usize, noreserve: bool) -> Result<*mut u8, IoError> {
+  let mut flags = STACK_FLAGS;
+  if noreserve { flags |= NORESERVE_FLAGS }
+
+  let ptr = mmap(ptr::null_mut(), len as size_t, STACK_PROT, flags, -1, 0);
   if ptr == MAP_FAILED {
     Err(IoError::last_os_error())
   } else {
@@ -36,8 +56,18 @@ pub unsafe fn map_stack(len: usize) -> Result<*mut u8, IoError> {
   }
 }
 
-pub unsafe fn protect_stack(ptr: *mut u8) -> Result<(), IoError> {
-  if mprotect(ptr as *mut c_void, page_size() as size_t, GUARD_PROT) == 0 {
+// Commits memory for the stack by touching every page. Unlike MAP_POPULATE,
+// this only prefaults the given range and works on every platform.
+pub unsafe fn prefault_stack(ptr: *mut u8, len: usize) {
+  let mut offset = 0;
+  while offset < len {
+    ptr::write_volatile(ptr.offset(offset as isize), 0);
+    offset += page_size()
+  }
+}
+
+pub unsafe fn protect_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  if mprotect(ptr as *mut c_void, len as size_t, GUARD_PROT) == 0 {
     Ok(())
   } else {
     Err(IoError::last_os_error())
@@ -52,6 +82,63 @@ pub unsafe fn unmap_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
   }
 }
 
+pub unsafe fn discard_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  advise_stack(ptr, len, DISCARD_ADVICE)
+}
+
+pub unsafe fn lock_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  if mlock(ptr as *const c_void, len as size_t) == 0 {
+    Ok(())
+  } else {
+    Err(IoError::last_os_error())
+  }
+}
+
+unsafe fn advise_stack(ptr: *mut u8, len: usize, advice: c_int) -> Result<(), IoError> {
+  if madvise(ptr as *mut c_void, len as size_t, advice) == 0 {
+    Ok(())
+  } else {
+    Err(IoError::last_os_error())
+  }
+}
+
+// Transparent huge pages only exist on Linux; elsewhere, the advice is moot.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub unsafe fn advise_huge_pages(ptr: *mut u8, len: usize, enable: bool) -> Result<(), IoError> {
+  advise_stack(ptr, len, if enable { libc::MADV_HUGEPAGE } else { libc::MADV_NOHUGEPAGE })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub unsafe fn advise_huge_pages(_ptr: *mut u8, _len: usize, _enable: bool) -> Result<(), IoError> {
+  Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub unsafe fn exclude_from_dump(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  advise_stack(ptr, len, libc::MADV_DONTDUMP)
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+pub unsafe fn exclude_from_dump(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  advise_stack(ptr, len, libc::MADV_NOCORE)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly")))]
+pub unsafe fn exclude_from_dump(_ptr: *mut u8, _len: usize) -> Result<(), IoError> {
+  Err(IoError::from_raw_os_error(libc::ENOTSUP))
+}
+
+pub unsafe fn resident_pages(ptr: *mut u8, len: usize) -> Result<usize, IoError> {
+  let mut pages: Vec<u8> = Vec::new();
+  pages.resize(len / page_size(), 0);
+  if mincore(ptr as *mut c_void, len as size_t, pages.as_mut_ptr() as *mut _) == 0 {
+    Ok(pages.iter().filter(|&&page| page & 1 != 0).count())
+  } else {
+    Err(IoError::last_os_error())
+  }
+}
+
 pub fn page_size() -> usize {
   #[cold]
   pub fn sys_page_size() -> usize {
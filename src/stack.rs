@@ -27,11 +27,23 @@ pub trait Stack {
   fn limit(&self) -> *mut u8;
 }
 
-/// A marker trait for `Stack` objects with a guard page.
+/// A trait for `Stack` objects with a guard area below the limit.
 ///
 /// To preserve memory safety, an implementation of this trait must fulfill
 /// the following contract, in addition to the [contract](trait.Stack.html) of `Stack`:
 ///
-///   * Any access of data at addresses `limit()` to `limit().offset(4096)` must
-///     abnormally terminate, at least, the thread that performs the access.
-pub unsafe trait GuardedStack {}
+///   * `guard_size()` must be at least 4096.
+///   * Any access of data at addresses `limit().offset(-guard_size())` to `limit()`
+///     must abnormally terminate, at least, the thread that performs the access.
+///
+/// A function whose stack frame is larger than the guard area could skip over it
+/// and access unrelated memory. On x86 and x86_64, Rust emits stack probes that
+/// touch every page of such a frame in order, so the guard area is always hit first.
+/// On other architectures, a larger guard area lowers the risk.
+pub unsafe trait GuardedStack {
+  /// Returns the size of the guard area, which lies immediately below the limit
+  /// address of the stack.
+  fn guard_size(&self) -> usize {
+    4096
+  }
+}
@@ -0,0 +1,97 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(feature = "canary")]
+extern crate fringe;
+
+use fringe::{Stack, GuardedStack, SliceStack};
+#[cfg(unix)]
+use fringe::OsStack;
+use fringe::generator::Generator;
+
+#[test]
+fn canary_intact() {
+  let mut memory = [0; 4096];
+  let stack = SliceStack(&mut memory);
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, |yielder, ()| {
+      for i in 0..3 { yielder.suspend(i) }
+    })
+  };
+  assert_eq!(gen.resume(()), Some(0));
+  assert_eq!(gen.resume(()), Some(1));
+  assert_eq!(gen.resume(()), Some(2));
+  assert_eq!(gen.resume(()), None);
+}
+
+#[test]
+#[should_panic(expected = "generator 'clobberer' has overflowed its stack")]
+fn canary_clobbered() {
+  let mut memory = [0; 4096];
+  // Simulate an overflow by writing to the bottom of the stack directly.
+  let bottom = memory.as_mut_ptr() as usize;
+  let stack = SliceStack(&mut memory);
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, move |yielder, ()| {
+      *(bottom as *mut u8) = 0;
+      yielder.suspend(())
+    })
+  };
+  gen.set_name("clobberer");
+  gen.resume(());
+}
+
+// Pretends to have a guard page, so that `Generator::new` accepts it.
+struct Guarded<'a>(SliceStack<'a>);
+
+impl<'a> Stack for Guarded<'a> {
+  fn base(&self) -> *mut u8 { self.0.base() }
+  fn limit(&self) -> *mut u8 { self.0.limit() }
+}
+
+unsafe impl<'a> GuardedStack for Guarded<'a> {}
+
+#[test]
+fn guarded_stack_untouched() {
+  let mut memory = [0; 4096];
+  let bottom = memory.as_mut_ptr() as usize;
+  let stack = Guarded(SliceStack(&mut memory));
+  let mut gen = Generator::new(stack, move |yielder, ()| {
+    // No canary has been placed, so neither is it checked.
+    for offset in 0..128 {
+      assert_eq!(unsafe { *((bottom + offset) as *const u8) }, 0);
+    }
+    unsafe { *(bottom as *mut u8) = 1 }
+    yielder.suspend(())
+  });
+  assert_eq!(gen.resume(()), Some(()));
+}
+
+#[test]
+#[cfg(unix)]
+fn canary_survives_trim() {
+  #[inline(never)]
+  fn use_stack() {
+    let mut memory = [0u8; 1 << 16];
+    for byte in memory.iter_mut() {
+      unsafe { std::ptr::write_volatile(byte, 1) }
+    }
+  }
+
+  let stack = OsStack::new(1 << 18).unwrap();
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, |yielder, ()| {
+      use_stack();
+      yielder.suspend(());
+      use_stack();
+    })
+  };
+  gen.resume(());
+  // Release the unused part of the stack; the canary at its bottom
+  // must be left intact.
+  gen.trim_stack().unwrap();
+  assert_eq!(gen.resume(()), None);
+}
@@ -7,7 +7,7 @@
 // copied, modified, or distributed except according to those terms.
 extern crate fringe;
 
-use fringe::{SliceStack, OwnedStack, OsStack};
+use fringe::{SliceStack, OwnedStack, OsStack, StackPool, StackArena};
 use fringe::generator::{Generator, Yielder};
 
 fn add_one_fn(yielder: &mut Yielder<i32, i32>, mut input: i32) {
@@ -83,6 +83,59 @@ fn with_owned_stack() {
   assert_eq!(add_one.resume(2), Some(3));
 }
 
+#[test]
+fn with_pooled_stack() {
+  let stack = StackPool::get(0).unwrap();
+  let mut add_one = Generator::new(stack, add_one_fn);
+  assert_eq!(add_one.resume(1), Some(2));
+  assert_eq!(add_one.resume(0), None);
+  let stack = add_one.unwrap();
+  let mut add_one = Generator::new(stack, add_one_fn);
+  assert_eq!(add_one.resume(2), Some(3));
+}
+
+#[test]
+fn with_arena_stack() {
+  let arena = StackArena::new(0, 2).unwrap();
+  let mut add_one = Generator::new(arena.get().unwrap(), add_one_fn);
+  let mut add_two = Generator::new(arena.get().unwrap(), add_one_fn);
+  assert_eq!(add_one.resume(1), Some(2));
+  assert_eq!(add_two.resume(2), Some(3));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn trim_stack() {
+  #[inline(never)]
+  fn use_stack() {
+    let mut memory = [0u8; 1 << 18];
+    for byte in memory.iter_mut() {
+      unsafe { std::ptr::write_volatile(byte, 1) }
+    }
+  }
+
+  let stack = OsStack::new(1 << 20).unwrap();
+  let mut gen = Generator::new(stack, |yielder, ()| {
+    use_stack();
+    yielder.suspend(());
+  });
+  gen.resume(());
+
+  let stats = gen.stack_stats().unwrap();
+  assert_eq!(stats.reserved, 1 << 20);
+  assert!(stats.committed >= 1 << 18);
+
+  gen.trim_stack().unwrap();
+  assert!(gen.stack_stats().unwrap().committed < 1 << 16);
+
+  // The generator keeps working after its stack has been trimmed.
+  assert_eq!(gen.resume(()), None);
+
+  // Once the generator function has returned, the whole stack is released.
+  gen.trim_stack().unwrap();
+  assert_eq!(gen.stack_stats().unwrap().committed, 0);
+}
+
 #[test]
 fn forget_yielded() {
   struct Dropper(*mut bool);
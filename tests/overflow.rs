@@ -0,0 +1,127 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+#![feature(test)]
+extern crate test;
+extern crate libc;
+extern crate fringe;
+
+use std::env;
+use std::process::{Command, Output};
+use std::os::unix::process::ExitStatusExt;
+use fringe::{Stack, GuardedStack, OsStack, OsStackBuilder, Generator};
+
+// Each test runs its crashing part in a child process, which is this same
+// test binary running only that test, with CHILD_VAR set.
+const CHILD_VAR: &'static str = "FRINGE_OVERFLOW_TEST_CHILD";
+
+fn run_child(test_name: &str) -> Output {
+  Command::new(env::current_exe().unwrap())
+    .arg(test_name)
+    .arg("--exact")
+    .arg("--test-threads=1")
+    .env(CHILD_VAR, "1")
+    .output()
+    .unwrap()
+}
+
+fn is_child() -> bool {
+  env::var_os(CHILD_VAR).is_some()
+}
+
+fn recurse(depth: usize) -> usize {
+  let frame = [depth; 16];
+  test::black_box(&frame);
+  recurse(test::black_box(depth + 1)) + frame[depth % 16]
+}
+
+#[test]
+fn overflow_reported() {
+  if is_child() {
+    fringe::install_overflow_handler().unwrap();
+    let stack = OsStack::new(1 << 16).unwrap();
+    let mut gen = Generator::new(stack, |_yielder, ()| { recurse(0); });
+    gen.set_name("recurser");
+    gen.resume(());
+    unreachable!();
+  }
+
+  let output = run_child("overflow_reported");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("generator 'recurser' has overflowed its stack (65536 bytes)"),
+          "unexpected output: {}", stderr);
+  assert_eq!(output.status.signal(), Some(libc::SIGABRT));
+}
+
+// A frame much larger than the guard page; on x86 and x86_64, stack probes make
+// sure that it is touched from the top down, page by page.
+#[inline(never)]
+fn large_frame() {
+  let frame = [0u8; 1 << 20];
+  test::black_box(&frame);
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn large_frame_hits_guard() {
+  if is_child() {
+    fringe::install_overflow_handler().unwrap();
+    let stack = OsStack::new(1 << 16).unwrap();
+    let mut gen = Generator::new(stack, |_yielder, ()| large_frame());
+    gen.set_name("large frame");
+    gen.resume(());
+    unreachable!();
+  }
+
+  let output = run_child("large_frame_hits_guard");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("generator 'large frame' has overflowed its stack"),
+          "unexpected output: {}", stderr);
+  assert_eq!(output.status.signal(), Some(libc::SIGABRT));
+}
+
+#[test]
+fn multi_page_guard_reported() {
+  if is_child() {
+    fringe::install_overflow_handler().unwrap();
+    let stack = OsStackBuilder::new(1 << 16).guard_pages(16).build().unwrap();
+    // Fault in the lowest guard page rather than the first one below the stack,
+    // which is only reported if the whole guard area has been registered.
+    let page_size = stack.guard_size() / 16;
+    let target = unsafe { stack.limit().offset(-(15 * page_size as isize)) } as usize;
+    let mut gen = Generator::new(stack, move |_yielder, ()| {
+      unsafe { *(target as *mut u8) = 0 }
+    });
+    gen.set_name("multi-page guard");
+    gen.resume(());
+    unreachable!();
+  }
+
+  let output = run_child("multi_page_guard_reported");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("generator 'multi-page guard' has overflowed its stack (65536 bytes)"),
+          "unexpected output: {}", stderr);
+  assert_eq!(output.status.signal(), Some(libc::SIGABRT));
+}
+
+#[test]
+fn other_faults_chained() {
+  if is_child() {
+    fringe::install_overflow_handler().unwrap();
+    // This guard page does not belong to any generator, so the fault
+    // must be handled as if the overflow handler was not installed.
+    let stack = OsStack::new(0).unwrap();
+    unsafe { *stack.limit().offset(-1) = 0 }
+    unreachable!();
+  }
+
+  let output = run_child("other_faults_chained");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(!stderr.contains("has overflowed its stack"), "unexpected output: {}", stderr);
+  assert!(output.status.signal() == Some(libc::SIGSEGV) ||
+          output.status.signal() == Some(libc::SIGBUS));
+}
@@ -0,0 +1,92 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) Nathan Zadoks <nathan@nathan7.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate libc;
+extern crate fringe;
+
+use std::thread;
+use std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+use std::sync::mpsc::channel;
+use fringe::{Stack, StackPool, PoolConfig};
+
+// The pool limits are global to the process, so the tests in this file
+// must not run concurrently with each other.
+struct Configured(MutexGuard<'static, ()>);
+
+impl Drop for Configured {
+  fn drop(&mut self) {
+    StackPool::configure(PoolConfig::default());
+    StackPool::clear();
+  }
+}
+
+fn configure(config: PoolConfig) -> Configured {
+  static INIT: Once = ONCE_INIT;
+  static mut LOCK: *const Mutex<()> = 0 as *const _;
+
+  let guard = unsafe {
+    INIT.call_once(|| LOCK = Box::into_raw(Box::new(Mutex::new(()))));
+    (*LOCK).lock().unwrap_or_else(|err| err.into_inner())
+  };
+  StackPool::clear();
+  StackPool::configure(config);
+  assert_eq!(StackPool::config(), config);
+  Configured(guard)
+}
+
+#[test]
+fn default_config() {
+  let _config = configure(PoolConfig::default());
+  assert_eq!(StackPool::config(), PoolConfig { thread_cache: 16, global_cache: 64 });
+}
+
+#[test]
+fn recycled_through_global_pool() {
+  let _config = configure(PoolConfig { thread_cache: 0, global_cache: 64 });
+
+  // With no thread cache, the freed stack goes straight to the global pool,
+  // where any other thread can take it.
+  let base = thread::spawn(|| {
+    StackPool::get(1 << 16).unwrap().base() as usize
+  }).join().unwrap();
+  let stack = StackPool::get(1 << 16).unwrap();
+  assert_eq!(stack.base() as usize, base);
+}
+
+#[test]
+fn thread_cache_flushed_on_exit() {
+  let _config = configure(PoolConfig::default());
+
+  let (base_tx, base_rx) = channel();
+  let (exit_tx, exit_rx) = channel();
+  let thread = thread::spawn(move || {
+    let base = StackPool::get(1 << 16).unwrap().base() as usize;
+    base_tx.send(base).unwrap();
+    exit_rx.recv().unwrap();
+  });
+
+  // While the other thread is alive, its cached stack is not available here.
+  let base = base_rx.recv().unwrap();
+  let other = StackPool::get(1 << 16).unwrap();
+  assert!(other.base() as usize != base);
+
+  // Once it has exited, its cache has been moved to the global pool.
+  exit_tx.send(()).unwrap();
+  thread.join().unwrap();
+  let stack = StackPool::get(1 << 16).unwrap();
+  assert_eq!(stack.base() as usize, base);
+}
+
+#[test]
+fn unmapped_without_global_pool() {
+  let _config = configure(PoolConfig { thread_cache: 0, global_cache: 0 });
+
+  let limit = StackPool::get(1 << 16).unwrap().limit();
+  // msync fails with ENOMEM if the memory is no longer mapped.
+  let result = unsafe { libc::msync(limit as *mut libc::c_void, 4096, libc::MS_ASYNC) };
+  assert_eq!(result, -1);
+}
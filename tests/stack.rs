@@ -5,8 +5,12 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 extern crate fringe;
+#[cfg(target_os = "linux")]
+extern crate libc;
 
-use fringe::{Stack, SliceStack, OwnedStack, OsStack};
+use std::io::ErrorKind;
+use fringe::{Stack, GuardedStack, SliceStack, OwnedStack, OsStack, OsStackBuilder, OsStackError};
+use fringe::{StackPool, StackArena};
 
 #[test]
 fn slice_stack() {
@@ -34,3 +38,124 @@ fn one_page_os_stack() {
   // Make sure the topmost page of the stack, at least, is accessible.
   unsafe { *(stack.base().offset(-1)) = 0; }
 }
+
+#[test]
+fn built_os_stack() {
+  let stack = OsStackBuilder::new(1 << 16)
+    .guard_pages(4)
+    .prefault(true)
+    .huge_pages(false)
+    .noreserve(true)
+    .build()
+    .unwrap();
+  assert_eq!(stack.base() as usize - stack.limit() as usize, 1 << 16);
+  // Make sure the lowest and topmost pages of the stack are accessible.
+  unsafe { *stack.limit() = 0; }
+  unsafe { *(stack.base().offset(-1)) = 0; }
+}
+
+#[test]
+fn multi_page_guard_os_stack() {
+  let page_size = OsStack::new(0).unwrap().guard_size();
+  assert!(page_size >= 4096);
+  let stack = OsStackBuilder::new(0).guard_pages(4).build().unwrap();
+  assert_eq!(stack.guard_size(), 4 * page_size);
+  unsafe { *(stack.base().offset(-1)) = 0; }
+}
+
+#[test]
+fn huge_os_stack() {
+  match OsStackBuilder::new(usize::max_value()).build() {
+    Err(OsStackError::Size) => (),
+    result => panic!("unexpected result: {:?}", result)
+  }
+}
+
+#[test]
+fn clamped_guard_pages_os_stack() {
+  let page_size = OsStack::new(0).unwrap().guard_size();
+  let stack = OsStackBuilder::new(0).guard_pages(usize::max_value()).noreserve(true).build().unwrap();
+  assert_eq!(stack.guard_size(), 4096 * page_size);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn prefaulted_os_stack() {
+  let stack = OsStackBuilder::new(1 << 16).guard_pages(16).prefault(true).build().unwrap();
+  let page_size = stack.guard_size() / 16;
+  let start = unsafe { stack.limit().offset(-(stack.guard_size() as isize)) };
+  let len = stack.base() as usize - start as usize;
+  let mut resident = vec![0u8; len / page_size];
+  assert_eq!(unsafe { libc::mincore(start as *mut libc::c_void, len, resident.as_mut_ptr()) }, 0);
+  // Only the stack itself is prefaulted, not the guard pages.
+  let (guard, data) = resident.split_at(16);
+  assert!(guard.iter().all(|&page| page & 1 == 0));
+  assert!(data.iter().all(|&page| page & 1 == 1));
+}
+
+#[test]
+fn locked_os_stack() {
+  match OsStackBuilder::new(0).lock(true).build() {
+    Ok(stack) => unsafe { *(stack.base().offset(-1)) = 0; },
+    // The limit on locked memory can be very low, or zero.
+    Err(OsStackError::Lock(_)) => (),
+    Err(err) => panic!("{}", err)
+  }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn dontdump_os_stack() {
+  let stack = OsStackBuilder::new(0).exclude_from_core_dumps(true).build().unwrap();
+  unsafe { *(stack.base().offset(-1)) = 0; }
+}
+
+#[test]
+fn pooled_stack() {
+  let stack = StackPool::get(0).unwrap();
+  // Make sure the topmost page of the stack, at least, is accessible.
+  unsafe { *(stack.base().offset(-1)) = 0; }
+}
+
+#[test]
+fn pooled_stack_recycled() {
+  let base = StackPool::get(1 << 16).unwrap().base();
+  // The freed stack is cached by this thread, so it is handed out again.
+  let stack = StackPool::get(1 << 16).unwrap();
+  assert_eq!(stack.base(), base);
+  assert!(stack.base() as usize - stack.limit() as usize >= 1 << 16);
+}
+
+#[test]
+fn pooled_stack_invalid() {
+  assert_eq!(StackPool::get(usize::max_value()).unwrap_err().kind(), ErrorKind::InvalidInput);
+  // This size class fits into the address space, but cannot be mapped.
+  assert!(StackPool::get(usize::max_value() / 2 + 1).is_err());
+}
+
+#[test]
+fn arena_stack() {
+  let arena = StackArena::new(0, 4).unwrap();
+  let stacks: Vec<_> = (0..4).map(|_| arena.get().unwrap()).collect();
+  assert!(arena.get().is_none());
+  for stack in &stacks {
+    // Make sure the topmost page of the stack, at least, is accessible.
+    unsafe { *(stack.base().offset(-1)) = 1; }
+  }
+  drop(stacks);
+  assert_eq!(arena.available(), 4);
+
+  // On Linux, a freed stack reads as zero after it is released to the operating system.
+  let stack = arena.get().unwrap();
+  if cfg!(target_os = "linux") {
+    assert_eq!(unsafe { *(stack.base().offset(-1)) }, 0);
+  }
+}
+
+#[test]
+fn arena_invalid() {
+  assert_eq!(StackArena::new(0, 0).unwrap_err().kind(), ErrorKind::InvalidInput);
+  assert_eq!(StackArena::new(1 << 20, usize::max_value()).unwrap_err().kind(),
+             ErrorKind::InvalidInput);
+  assert_eq!(StackArena::new(usize::max_value(), 1).unwrap_err().kind(), ErrorKind::InvalidInput);
+}